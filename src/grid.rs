@@ -1,6 +1,13 @@
 use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
 use base64::{Engine as _, engine::general_purpose};
+use futures::StreamExt;
+use lru::LruCache;
 use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug)]
 pub enum GridError {
@@ -14,7 +21,89 @@ pub enum GridError {
 #[derive(Debug)]
 pub struct DownloadResult {
     pub url: String,
-    pub data: Vec<u8>,
+    pub outcome: DownloadOutcome,
+}
+
+/// Outcome of downloading a single source URL, after exhausting retries.
+#[derive(Debug)]
+pub enum DownloadOutcome {
+    Success(Vec<u8>),
+    Failed(String),
+}
+
+/// Output encoding for a rendered grid, negotiated from an `Accept` header or `?format=` query param.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Avif,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Jpeg
+    }
+}
+
+impl OutputFormat {
+    /// Parses a bare MIME type such as `image/webp`; a value still carrying `;q=` or other
+    /// parameters is not recognized, so callers strip them first.
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match mime.trim() {
+            "image/webp" => Some(OutputFormat::WebP),
+            "image/png" => Some(OutputFormat::Png),
+            "image/avif" => Some(OutputFormat::Avif),
+            "image/jpeg" | "image/jpg" => Some(OutputFormat::Jpeg),
+            _ => None,
+        }
+    }
+
+    /// Parses a short name such as `"webp"`, as used by the `?format=` query param.
+    pub fn from_query(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "webp" => Some(OutputFormat::WebP),
+            "png" => Some(OutputFormat::Png),
+            "avif" => Some(OutputFormat::Avif),
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            _ => None,
+        }
+    }
+
+    /// Picks the first supported type from a comma-separated `Accept` header, in the order listed;
+    /// `q=` weights are stripped and not used for ranking.
+    pub fn from_accept_header(accept: &str) -> Option<Self> {
+        accept
+            .split(',')
+            .filter_map(|part| Self::from_mime(part.split(';').next().unwrap_or("")))
+            .next()
+    }
+
+    pub fn image_format(self) -> image::ImageFormat {
+        match self {
+            OutputFormat::Jpeg => image::ImageFormat::Jpeg,
+            OutputFormat::Png => image::ImageFormat::Png,
+            OutputFormat::WebP => image::ImageFormat::WebP,
+            OutputFormat::Avif => image::ImageFormat::Avif,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Png => "image/png",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+}
+
+/// Encodes a rendered grid into the requested output format.
+pub fn encode_grid(image: &RgbaImage, format: OutputFormat) -> Result<Vec<u8>, GridError> {
+    let mut buf = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut buf);
+    DynamicImage::ImageRgba8(image.clone()).write_to(&mut cursor, format.image_format())?;
+    Ok(buf)
 }
 
 impl std::fmt::Display for GridError {
@@ -55,17 +144,36 @@ impl From<std::string::FromUtf8Error> for GridError {
     }
 }
 
-pub fn create_image_grid(image_bytes: &[&[u8]]) -> Result<RgbaImage, GridError> {
-    if image_bytes.is_empty() {
+/// Discrete cell sizes selectable via `?size=`; the largest replaces the old unbounded layout.
+pub const CELL_SIZE_LADDER: [u32; 5] = [80, 160, 320, 640, 1080];
+pub const DEFAULT_CELL_SIZE: u32 = 320;
+
+/// Parses a `?size=` value, accepting only sizes on [`CELL_SIZE_LADDER`].
+pub fn parse_cell_size(value: &str) -> Option<u32> {
+    value
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|size| CELL_SIZE_LADDER.contains(size))
+}
+
+pub fn create_image_grid(
+    downloads: &[DownloadResult],
+    cell_size: u32,
+) -> Result<RgbaImage, GridError> {
+    if downloads.is_empty() {
         return Err(GridError::EmptyInput);
     }
 
-    let images: Result<Vec<RgbaImage>, GridError> = image_bytes
+    let images: Result<Vec<RgbaImage>, GridError> = downloads
         .par_iter()
-        .map(|bytes| {
-            image::load_from_memory(bytes)
-                .map(|img| img.to_rgba8())
-                .map_err(GridError::from)
+        .map(|result| match &result.outcome {
+            DownloadOutcome::Success(bytes) => match decode_with_limits(bytes)? {
+                Decoded::Image(image) => Ok(image),
+                // A pixel-bomb reject is a slot-level problem, not a request-level one.
+                Decoded::TooLarge => Ok(placeholder_cell(cell_size)),
+            },
+            DownloadOutcome::Failed(_) => Ok(placeholder_cell(cell_size)),
         })
         .collect();
 
@@ -73,11 +181,8 @@ pub fn create_image_grid(image_bytes: &[&[u8]]) -> Result<RgbaImage, GridError>
 
     let (cols, rows) = calculate_grid_dimensions(images.len());
 
-    let max_width = images.iter().map(|img| img.width()).max().unwrap_or(0);
-    let max_height = images.iter().map(|img| img.height()).max().unwrap_or(0);
-
-    let grid_width = cols * max_width;
-    let grid_height = rows * max_height;
+    let grid_width = cols * cell_size;
+    let grid_height = rows * cell_size;
 
     let mut grid_image: RgbaImage = ImageBuffer::from_pixel(
         grid_width,
@@ -89,15 +194,59 @@ pub fn create_image_grid(image_bytes: &[&[u8]]) -> Result<RgbaImage, GridError>
         let col = (idx as u32) % cols;
         let row = (idx as u32) / cols;
 
-        let x_offset = col * max_width;
-        let y_offset = row * max_height;
+        let cell = fit_to_cell(img, cell_size);
+
+        let x_offset = col * cell_size + (cell_size - cell.width()) / 2;
+        let y_offset = row * cell_size + (cell_size - cell.height()) / 2;
 
-        image::imageops::overlay(&mut grid_image, img, x_offset as i64, y_offset as i64);
+        image::imageops::overlay(&mut grid_image, &cell, x_offset as i64, y_offset as i64);
     }
 
     Ok(grid_image)
 }
 
+/// Resizes `img` (preserving aspect ratio) to fit within a `cell_size` x `cell_size` box.
+fn fit_to_cell(img: &RgbaImage, cell_size: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+
+    let scale = (cell_size as f32 / width as f32).min(cell_size as f32 / height as f32);
+    let new_width = ((width as f32 * scale).round() as u32).max(1);
+    let new_height = ((height as f32 * scale).round() as u32).max(1);
+
+    image::imageops::resize(
+        img,
+        new_width,
+        new_height,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
+
+/// A neutral gray cell with an "X" mark, rendered in place of a source that failed to download.
+fn placeholder_cell(cell_size: u32) -> RgbaImage {
+    let mut cell: RgbaImage =
+        ImageBuffer::from_pixel(cell_size, cell_size, Rgba([200, 200, 200, 255]));
+
+    let size = cell_size as i64;
+    let thickness = (size / 40).max(1);
+    for x in 0..size {
+        for dy in -thickness..=thickness {
+            set_pixel_checked(&mut cell, x, x + dy, Rgba([120, 120, 120, 255]));
+            set_pixel_checked(&mut cell, x, size - 1 - x + dy, Rgba([120, 120, 120, 255]));
+        }
+    }
+
+    cell
+}
+
+fn set_pixel_checked(image: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
+
 fn calculate_grid_dimensions(count: usize) -> (u32, u32) {
     match count {
         0 => (0, 0),
@@ -111,46 +260,434 @@ fn calculate_grid_dimensions(count: usize) -> (u32, u32) {
     }
 }
 
+/// Decoded-pixel-bomb guard: caps an RGBA8 decode at roughly this many pixels (4 bytes each).
+const MAX_DECODE_PIXELS: u64 = 40_000_000;
+
+/// Result of a size-guarded decode: a pixel-bomb reject is reported distinctly so the caller
+/// can placeholder just that slot instead of treating it like a genuine decode failure.
+enum Decoded {
+    Image(RgbaImage),
+    TooLarge,
+}
+
+/// Decodes `bytes` with guessed format, reporting images whose declared dimensions would blow
+/// past [`MAX_DECODE_PIXELS`] as [`Decoded::TooLarge`] before any pixels are actually rasterized.
+fn decode_with_limits(bytes: &[u8]) -> Result<Decoded, GridError> {
+    let mut limits = image::io::Limits::default();
+    limits.max_alloc = Some(MAX_DECODE_PIXELS * 4);
+
+    let mut reader = image::io::Reader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| GridError::ImageDecodeError(image::ImageError::IoError(e)))?;
+    reader.limits(limits);
+
+    match reader.decode() {
+        Ok(img) => Ok(Decoded::Image(img.to_rgba8())),
+        Err(image::ImageError::Limits(_)) => Ok(Decoded::TooLarge),
+        Err(other) => Err(GridError::ImageDecodeError(other)),
+    }
+}
+
+/// Where a grid's watermark/copyright mark is placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatermarkPlacement {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Tiled,
+}
+
+impl WatermarkPlacement {
+    /// Parses an `IMAGEPREVIEW_WATERMARK_PLACEMENT` value such as `"bottom-right"` or `"tiled"`.
+    pub fn from_config_str(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "top-left" => Some(Self::TopLeft),
+            "top-right" => Some(Self::TopRight),
+            "bottom-left" => Some(Self::BottomLeft),
+            "bottom-right" => Some(Self::BottomRight),
+            "tiled" => Some(Self::Tiled),
+            _ => None,
+        }
+    }
+}
+
+/// Optional watermark/copyright mark composited onto every rendered grid.
+pub struct WatermarkConfig {
+    pub image: RgbaImage,
+    pub placement: WatermarkPlacement,
+    pub opacity: f32,
+}
+
+const WATERMARK_PATH_ENV: &str = "IMAGEPREVIEW_WATERMARK_PATH";
+const WATERMARK_PLACEMENT_ENV: &str = "IMAGEPREVIEW_WATERMARK_PLACEMENT";
+const WATERMARK_OPACITY_ENV: &str = "IMAGEPREVIEW_WATERMARK_OPACITY";
+const DEFAULT_WATERMARK_PLACEMENT: WatermarkPlacement = WatermarkPlacement::BottomRight;
+const DEFAULT_WATERMARK_OPACITY: f32 = 0.5;
+const WATERMARK_MARGIN: i64 = 8;
+
+impl WatermarkConfig {
+    /// Loads a watermark from `IMAGEPREVIEW_WATERMARK_PATH` if set; operators who don't need
+    /// branding simply leave the variable unset. A set-but-unloadable path (typo, missing
+    /// file, corrupt image) is logged rather than silently dropped.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var(WATERMARK_PATH_ENV).ok()?;
+        let image = match image::open(&path) {
+            Ok(image) => image.to_rgba8(),
+            Err(e) => {
+                tracing::warn!("failed to load watermark image from {}: {}", path, e);
+                return None;
+            }
+        };
+
+        let placement = std::env::var(WATERMARK_PLACEMENT_ENV)
+            .ok()
+            .and_then(|value| WatermarkPlacement::from_config_str(&value))
+            .unwrap_or(DEFAULT_WATERMARK_PLACEMENT);
+
+        let opacity = std::env::var(WATERMARK_OPACITY_ENV)
+            .ok()
+            .and_then(|value| value.parse::<f32>().ok())
+            .unwrap_or(DEFAULT_WATERMARK_OPACITY);
+
+        Some(Self {
+            image,
+            placement,
+            opacity,
+        })
+    }
+}
+
+/// Returns a copy of `image` with every pixel's alpha scaled by `opacity`.
+fn scale_alpha(image: &RgbaImage, opacity: f32) -> RgbaImage {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mut scaled = image.clone();
+    for pixel in scaled.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+    scaled
+}
+
+fn corner_offset(grid: &RgbaImage, mark: &RgbaImage, placement: WatermarkPlacement) -> (i64, i64) {
+    let (grid_width, grid_height) = grid.dimensions();
+    let (mark_width, mark_height) = mark.dimensions();
+
+    match placement {
+        WatermarkPlacement::TopLeft => (WATERMARK_MARGIN, WATERMARK_MARGIN),
+        WatermarkPlacement::TopRight => (
+            grid_width as i64 - mark_width as i64 - WATERMARK_MARGIN,
+            WATERMARK_MARGIN,
+        ),
+        WatermarkPlacement::BottomLeft => (
+            WATERMARK_MARGIN,
+            grid_height as i64 - mark_height as i64 - WATERMARK_MARGIN,
+        ),
+        WatermarkPlacement::BottomRight | WatermarkPlacement::Tiled => (
+            grid_width as i64 - mark_width as i64 - WATERMARK_MARGIN,
+            grid_height as i64 - mark_height as i64 - WATERMARK_MARGIN,
+        ),
+    }
+}
+
+/// Alpha-blends `config`'s watermark onto `grid_image`, tiling it if requested.
+fn apply_watermark(grid_image: &mut RgbaImage, config: &WatermarkConfig) {
+    let mark = scale_alpha(&config.image, config.opacity);
+    let (mark_width, mark_height) = mark.dimensions();
+    if mark_width == 0 || mark_height == 0 {
+        return;
+    }
+
+    if config.placement == WatermarkPlacement::Tiled {
+        let (grid_width, grid_height) = grid_image.dimensions();
+        let mut y = 0i64;
+        while y < grid_height as i64 {
+            let mut x = 0i64;
+            while x < grid_width as i64 {
+                image::imageops::overlay(grid_image, &mark, x, y);
+                x += mark_width as i64;
+            }
+            y += mark_height as i64;
+        }
+    } else {
+        let (x, y) = corner_offset(grid_image, &mark, config.placement);
+        image::imageops::overlay(grid_image, &mark, x, y);
+    }
+}
+
+/// Maximum attempts per source URL before it's recorded as a failed download.
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 3;
+/// Backoff between retries, scaled linearly by attempt number.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+/// Default per-request timeout for the downloading `reqwest::Client`.
+const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+/// Download-bomb guard: a source response larger than this is rejected outright.
+const MAX_DOWNLOAD_BYTES: u64 = 25 * 1024 * 1024;
+
+const SUPPORTED_IMAGE_CONTENT_TYPES: [&str; 6] = [
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "image/gif",
+    "image/bmp",
+    "image/avif",
+];
+
+fn is_supported_image_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    SUPPORTED_IMAGE_CONTENT_TYPES.contains(&mime)
+}
+
+/// A single download attempt's failure mode; only [`Network`](Self::Network) is retried.
+enum DownloadAttemptError {
+    Network(reqwest::Error),
+    TooLarge,
+    UnsupportedContentType(String),
+}
+
+/// Capacity of the source-URL -> raw-bytes LRU.
+const DOWNLOAD_CACHE_CAPACITY: usize = 256;
+/// Capacity of the normalized-request -> encoded-grid LRU.
+const RENDER_CACHE_CAPACITY: usize = 128;
+/// `Cache-Control: max-age=` advertised for rendered previews.
+pub const CACHE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// An encoded grid plus the headers needed to make it cacheable by clients and CDNs.
+pub struct RenderedPreview {
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub etag: String,
+    /// When this exact rendering was produced; used for the `Last-Modified` header instead of
+    /// process start time so it reflects this specific request/format/size combination.
+    pub rendered_at: SystemTime,
+}
+
+/// Raw source bytes plus when they were fetched, so [`CACHE_MAX_AGE`] can expire stale entries.
+struct CachedDownload {
+    bytes: Vec<u8>,
+    cached_at: SystemTime,
+}
+
+/// True once `cached_at` is older than [`CACHE_MAX_AGE`]; used to treat a present-but-stale
+/// cache entry as a miss rather than relying on LRU capacity alone to bound freshness.
+fn is_stale(cached_at: SystemTime) -> bool {
+    SystemTime::now()
+        .duration_since(cached_at)
+        .map(|age| age > CACHE_MAX_AGE)
+        .unwrap_or(false)
+}
+
 pub struct ImageService {
     client: reqwest::Client,
+    download_cache: Mutex<LruCache<String, CachedDownload>>,
+    render_cache: Mutex<LruCache<String, RenderedPreview>>,
+    watermark: Option<WatermarkConfig>,
+}
+
+impl Clone for RenderedPreview {
+    fn clone(&self) -> Self {
+        Self {
+            bytes: self.bytes.clone(),
+            content_type: self.content_type,
+            etag: self.etag.clone(),
+            rendered_at: self.rendered_at,
+        }
+    }
 }
 
 impl ImageService {
     pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_DOWNLOAD_TIMEOUT)
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_config(timeout, None)
+    }
+
+    pub fn with_watermark(watermark: Option<WatermarkConfig>) -> Self {
+        Self::with_config(DEFAULT_DOWNLOAD_TIMEOUT, watermark)
+    }
+
+    pub fn with_config(timeout: Duration, watermark: Option<WatermarkConfig>) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap_or_default(),
+            download_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(DOWNLOAD_CACHE_CAPACITY).unwrap(),
+            )),
+            render_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(RENDER_CACHE_CAPACITY).unwrap(),
+            )),
+            watermark,
+        }
+    }
+
+    /// Renders (or serves from cache) the encoded preview for a normalized request.
+    pub async fn render_preview(
+        &self,
+        base64_urls: &str,
+        format: OutputFormat,
+        cell_size: u32,
+    ) -> Result<RenderedPreview, GridError> {
+        let key = Self::render_cache_key(base64_urls, format, cell_size);
+
+        if let Some(cached) = self.render_cache.lock().unwrap().get(&key) {
+            if !is_stale(cached.rendered_at) {
+                return Ok(cached.clone());
+            }
         }
+
+        let image = self.process_base64_urls(base64_urls, cell_size).await?;
+        let bytes = encode_grid(&image, format)?;
+        let rendered = RenderedPreview {
+            etag: Self::etag_for(&key),
+            content_type: format.content_type(),
+            bytes,
+            rendered_at: SystemTime::now(),
+        };
+
+        self.render_cache
+            .lock()
+            .unwrap()
+            .put(key, rendered.clone());
+
+        Ok(rendered)
     }
 
-    pub async fn download_images(&self, urls: &[String]) -> Result<Vec<Vec<u8>>, GridError> {
+    fn render_cache_key(base64_urls: &str, format: OutputFormat, cell_size: u32) -> String {
+        format!("{}|{}|{}", base64_urls, format.content_type(), cell_size)
+    }
+
+    /// Derives a stable ETag from a hash of the normalized request key.
+    fn etag_for(key: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        format!("\"{:x}\"", hasher.finish())
+    }
+
+    pub async fn download_images(&self, urls: &[String]) -> Result<Vec<DownloadResult>, GridError> {
         if urls.is_empty() {
             return Err(GridError::EmptyInput);
         }
 
         let download_tasks: Vec<_> = urls
             .iter()
-            .map(|url| {
-                let client = self.client.clone();
-                let url = url.clone();
-                async move {
-                    let response = client.get(&url).send().await?;
-                    let bytes = response.bytes().await?;
-                    Ok::<Vec<u8>, reqwest::Error>(bytes.to_vec())
-                }
-            })
+            .map(|url| self.download_with_cache(url.clone()))
             .collect();
 
-        let results = futures::future::join_all(download_tasks).await;
+        Ok(futures::future::join_all(download_tasks).await)
+    }
 
-        let images: Result<Vec<Vec<u8>>, GridError> = results
-            .into_iter()
-            .map(|r| r.map_err(GridError::from))
-            .collect();
+    /// Serves a source URL from the download cache, falling back to a retried network fetch.
+    /// Entries older than [`CACHE_MAX_AGE`] are treated as a miss and refetched. A single
+    /// source's failure never aborts the rest of the grid; it's recorded on the
+    /// `DownloadResult` so `create_image_grid` can place a placeholder for that slot instead.
+    async fn download_with_cache(&self, url: String) -> DownloadResult {
+        if let Some(cached) = self.download_cache.lock().unwrap().get(&url) {
+            if !is_stale(cached.cached_at) {
+                return DownloadResult {
+                    url,
+                    outcome: DownloadOutcome::Success(cached.bytes.clone()),
+                };
+            }
+        }
+
+        let result = Self::download_with_retry(&self.client, url.clone()).await;
+
+        if let DownloadOutcome::Success(bytes) = &result.outcome {
+            self.download_cache.lock().unwrap().put(
+                url,
+                CachedDownload {
+                    bytes: bytes.clone(),
+                    cached_at: SystemTime::now(),
+                },
+            );
+        }
+
+        result
+    }
+
+    /// Downloads a single URL, retrying up to [`MAX_DOWNLOAD_ATTEMPTS`] times with a short backoff.
+    /// Validation failures (too large, unsupported content type) are not retried, but still land
+    /// as [`DownloadOutcome::Failed`] rather than aborting the whole batch.
+    async fn download_with_retry(client: &reqwest::Client, url: String) -> DownloadResult {
+        let mut last_error = String::new();
+
+        for attempt in 1..=MAX_DOWNLOAD_ATTEMPTS {
+            match Self::download_once(client, &url).await {
+                Ok(bytes) => {
+                    return DownloadResult {
+                        url,
+                        outcome: DownloadOutcome::Success(bytes),
+                    }
+                }
+                Err(DownloadAttemptError::TooLarge) => {
+                    last_error = "response exceeds the configured size limit".to_string();
+                    break;
+                }
+                Err(DownloadAttemptError::UnsupportedContentType(ct)) => {
+                    last_error = format!("unsupported content type: {}", ct);
+                    break;
+                }
+                Err(DownloadAttemptError::Network(e)) => {
+                    last_error = e.to_string();
+                    if attempt < MAX_DOWNLOAD_ATTEMPTS {
+                        tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+                    }
+                }
+            }
+        }
+
+        DownloadResult {
+            url,
+            outcome: DownloadOutcome::Failed(last_error),
+        }
+    }
+
+    async fn download_once(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, DownloadAttemptError> {
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(DownloadAttemptError::Network)?;
+
+        if let Some(len) = response.content_length() {
+            if len > MAX_DOWNLOAD_BYTES {
+                return Err(DownloadAttemptError::TooLarge);
+            }
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        if !content_type.is_empty() && !is_supported_image_content_type(&content_type) {
+            return Err(DownloadAttemptError::UnsupportedContentType(content_type));
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut buf = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(DownloadAttemptError::Network)?;
+            if buf.len() as u64 + chunk.len() as u64 > MAX_DOWNLOAD_BYTES {
+                return Err(DownloadAttemptError::TooLarge);
+            }
+            buf.extend_from_slice(&chunk);
+        }
 
-        images
+        Ok(buf)
     }
 
-    pub async fn process_base64_urls(&self, base64_urls: &str) -> Result<RgbaImage, GridError> {
+    pub async fn process_base64_urls(
+        &self,
+        base64_urls: &str,
+        cell_size: u32,
+    ) -> Result<RgbaImage, GridError> {
         let decoded_bytes = general_purpose::STANDARD
             .decode(base64_urls)
             .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(base64_urls))
@@ -165,9 +702,13 @@ impl ImageService {
 
         let downloaded = self.download_images(&urls).await?;
 
-        let refs: Vec<&[u8]> = downloaded.iter().map(|v| v.as_slice()).collect();
+        let mut grid_image = create_image_grid(&downloaded, cell_size)?;
+
+        if let Some(watermark) = &self.watermark {
+            apply_watermark(&mut grid_image, watermark);
+        }
 
-        create_image_grid(&refs)
+        Ok(grid_image)
     }
 }
 
@@ -197,7 +738,201 @@ mod tests {
 
     #[test]
     fn test_empty_input() {
-        let result = create_image_grid(&[]);
+        let result = create_image_grid(&[], DEFAULT_CELL_SIZE);
         assert!(matches!(result, Err(GridError::EmptyInput)));
     }
+
+    fn encode_test_png(size: u32, color: Rgba<u8>) -> Vec<u8> {
+        let image: RgbaImage = ImageBuffer::from_pixel(size, size, color);
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut std::io::Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_create_image_grid_with_mixed_outcomes() {
+        let good = encode_test_png(4, Rgba([10, 20, 30, 255]));
+        let downloads = vec![
+            DownloadResult {
+                url: "https://good.example/a.png".to_string(),
+                outcome: DownloadOutcome::Success(good),
+            },
+            DownloadResult {
+                url: "https://bad.example/b.png".to_string(),
+                outcome: DownloadOutcome::Failed("exhausted retries".to_string()),
+            },
+        ];
+
+        let grid = create_image_grid(&downloads, 40).expect("one failed slot shouldn't fail the grid");
+        assert_eq!(grid.dimensions(), (80, 80));
+
+        // first cell: the successfully downloaded image, scaled to fill its cell
+        assert_eq!(*grid.get_pixel(20, 20), Rgba([10, 20, 30, 255]));
+        // second cell: the placeholder's gray background, off the "X" mark
+        assert_eq!(grid.get_pixel(45, 20)[0], 200);
+    }
+
+    /// PNG CRC-32 (same polynomial/init/xorout as zlib's), used to patch up a tampered chunk.
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc: u32 = 0xFFFF_FFFF;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    /// Rewrites a PNG's declared IHDR width/height (and fixes up that chunk's CRC) without
+    /// touching the actual pixel data, so the decoder's declared-dimensions check fires before
+    /// it ever reads (now undersized) `IDAT` data.
+    fn with_oversized_header(png: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = png.to_vec();
+        let ihdr_data = 16; // 8-byte signature + 4-byte length + 4-byte "IHDR" type
+        bytes[ihdr_data..ihdr_data + 4].copy_from_slice(&width.to_be_bytes());
+        bytes[ihdr_data + 4..ihdr_data + 8].copy_from_slice(&height.to_be_bytes());
+
+        let crc_span = 12..ihdr_data + 13; // "IHDR" type + its 13 bytes of data
+        let crc = crc32(&bytes[crc_span.clone()]);
+        bytes[crc_span.end..crc_span.end + 4].copy_from_slice(&crc.to_be_bytes());
+
+        bytes
+    }
+
+    #[test]
+    fn test_create_image_grid_placeholders_a_pixel_bomb_slot() {
+        let good = encode_test_png(4, Rgba([10, 20, 30, 255]));
+        let bomb = with_oversized_header(&encode_test_png(4, Rgba([0, 0, 0, 255])), 60_000, 60_000);
+        let downloads = vec![
+            DownloadResult {
+                url: "https://good.example/a.png".to_string(),
+                outcome: DownloadOutcome::Success(good),
+            },
+            DownloadResult {
+                url: "https://bomb.example/b.png".to_string(),
+                outcome: DownloadOutcome::Success(bomb),
+            },
+        ];
+
+        let grid =
+            create_image_grid(&downloads, 40).expect("a decode-time pixel bomb shouldn't fail the grid");
+        assert_eq!(grid.dimensions(), (80, 80));
+
+        // first cell: the successfully decoded image, scaled to fill its cell
+        assert_eq!(*grid.get_pixel(20, 20), Rgba([10, 20, 30, 255]));
+        // second cell: the placeholder's gray background, off the "X" mark
+        assert_eq!(grid.get_pixel(45, 20)[0], 200);
+    }
+
+    #[test]
+    fn test_is_supported_image_content_type() {
+        assert!(is_supported_image_content_type("image/png"));
+        assert!(is_supported_image_content_type("image/jpeg; charset=binary"));
+        assert!(!is_supported_image_content_type("text/html"));
+    }
+
+    #[test]
+    fn test_decode_with_limits_rejects_garbage() {
+        let result = decode_with_limits(b"not an image");
+        assert!(matches!(result, Err(GridError::ImageDecodeError(_))));
+    }
+
+    #[test]
+    fn test_watermark_placement_from_config_str() {
+        assert_eq!(
+            WatermarkPlacement::from_config_str("bottom-right"),
+            Some(WatermarkPlacement::BottomRight)
+        );
+        assert_eq!(
+            WatermarkPlacement::from_config_str("Tiled"),
+            Some(WatermarkPlacement::Tiled)
+        );
+        assert_eq!(WatermarkPlacement::from_config_str("diagonal"), None);
+    }
+
+    #[test]
+    fn test_scale_alpha() {
+        let image: RgbaImage = ImageBuffer::from_pixel(2, 2, Rgba([0, 0, 0, 200]));
+        let scaled = scale_alpha(&image, 0.5);
+        assert_eq!(scaled.get_pixel(0, 0)[3], 100);
+    }
+
+    #[test]
+    fn test_parse_cell_size() {
+        assert_eq!(parse_cell_size("320"), Some(320));
+        assert_eq!(parse_cell_size("1080"), Some(1080));
+        assert_eq!(parse_cell_size("321"), None);
+        assert_eq!(parse_cell_size("not-a-number"), None);
+    }
+
+    #[test]
+    fn test_output_format_from_query() {
+        assert_eq!(OutputFormat::from_query("webp"), Some(OutputFormat::WebP));
+        assert_eq!(OutputFormat::from_query("PNG"), Some(OutputFormat::Png));
+        assert_eq!(OutputFormat::from_query("bogus"), None);
+    }
+
+    #[tokio::test]
+    async fn test_render_preview_serves_cache_hit_without_reprocessing() {
+        let service = ImageService::new();
+        let key = ImageService::render_cache_key("bogus!base64", OutputFormat::Jpeg, DEFAULT_CELL_SIZE);
+        let cached = RenderedPreview {
+            bytes: vec![1, 2, 3],
+            content_type: OutputFormat::Jpeg.content_type(),
+            etag: ImageService::etag_for(&key),
+            rendered_at: SystemTime::now(),
+        };
+        service.render_cache.lock().unwrap().put(key, cached.clone());
+
+        // "bogus!base64" isn't valid base64, so a cache miss here would return an error
+        // instead of real image bytes; a successful match proves the cache was actually hit.
+        let rendered = service
+            .render_preview("bogus!base64", OutputFormat::Jpeg, DEFAULT_CELL_SIZE)
+            .await
+            .expect("cached entry should be served without decoding base64_urls");
+
+        assert_eq!(rendered.bytes, cached.bytes);
+        assert_eq!(rendered.etag, cached.etag);
+    }
+
+    #[tokio::test]
+    async fn test_render_preview_refetches_stale_cache_entry() {
+        let service = ImageService::new();
+        let key = ImageService::render_cache_key("bogus!base64", OutputFormat::Jpeg, DEFAULT_CELL_SIZE);
+        let stale_at = SystemTime::now() - CACHE_MAX_AGE - Duration::from_secs(1);
+        let cached = RenderedPreview {
+            bytes: vec![1, 2, 3],
+            content_type: OutputFormat::Jpeg.content_type(),
+            etag: ImageService::etag_for(&key),
+            rendered_at: stale_at,
+        };
+        service.render_cache.lock().unwrap().put(key, cached);
+
+        // The stale entry must not be served as-is; falling through re-processes
+        // "bogus!base64" and fails on its invalid base64, proving it wasn't a cache hit.
+        let result = service
+            .render_preview("bogus!base64", OutputFormat::Jpeg, DEFAULT_CELL_SIZE)
+            .await;
+        assert!(matches!(result, Err(GridError::Base64DecodeError(_))));
+    }
+
+    #[test]
+    fn test_output_format_from_accept_header() {
+        assert_eq!(
+            OutputFormat::from_accept_header("image/webp,image/*;q=0.8"),
+            Some(OutputFormat::WebP)
+        );
+        assert_eq!(
+            OutputFormat::from_accept_header("text/html,image/png;q=0.9"),
+            Some(OutputFormat::Png)
+        );
+        assert_eq!(OutputFormat::from_accept_header("text/html"), None);
+    }
 }
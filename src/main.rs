@@ -1,12 +1,17 @@
 use axum::{
-    extract::{Path, State},
-    http::{header, StatusCode},
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
     Router,
 };
-use imagepreview::grid::ImageService;
+use imagepreview::grid::{
+    parse_cell_size, GridError, ImageService, OutputFormat, WatermarkConfig, CACHE_MAX_AGE,
+    DEFAULT_CELL_SIZE,
+};
+use serde::Deserialize;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tower_http::trace::{TraceLayer, DefaultMakeSpan, DefaultOnResponse};
 use tracing::Level;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -15,40 +20,100 @@ async fn hello_world() -> &'static str {
     "Hello, World!"
 }
 
+#[derive(Debug, Deserialize)]
+struct PreviewParams {
+    format: Option<String>,
+    size: Option<String>,
+}
+
+/// Resolves the response encoding: an explicit `?format=` wins, then the `Accept` header, then JPEG.
+fn negotiate_format(headers: &HeaderMap, query_format: Option<&str>) -> OutputFormat {
+    if let Some(format) = query_format.and_then(OutputFormat::from_query) {
+        return format;
+    }
+
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(OutputFormat::from_accept_header)
+        .unwrap_or_default()
+}
+
+/// True if the client's conditional headers show it already holds the current representation.
+///
+/// Per RFC 7232 §3.3, `If-Modified-Since` is only considered when `If-None-Match` is absent;
+/// an `If-None-Match` that doesn't match must not fall through to a date comparison.
+fn not_modified(headers: &HeaderMap, etag: &str, last_modified: SystemTime) -> bool {
+    if let Some(if_none_match) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        return if_none_match.split(',').any(|tag| tag.trim() == etag);
+    }
+
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .map(|since| {
+            // HTTP dates carry whole seconds only, so compare at that granularity.
+            let secs = |t: SystemTime| {
+                t.duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            };
+            secs(last_modified) <= secs(since)
+        })
+        .unwrap_or(false)
+}
+
 async fn preview_handler(
     State(service): State<Arc<ImageService>>,
     Path(base64_urls): Path<String>,
-) -> impl IntoResponse {
+    Query(params): Query<PreviewParams>,
+    headers: HeaderMap,
+) -> Response {
     let base64_urls = base64_urls.trim_start_matches('/');
+    let format = negotiate_format(&headers, params.format.as_deref());
+    let cell_size = params
+        .size
+        .as_deref()
+        .and_then(parse_cell_size)
+        .unwrap_or(DEFAULT_CELL_SIZE);
 
-    match service.process_base64_urls(base64_urls).await {
-        Ok(image) => {
-            let mut buf = Vec::new();
-            let mut cursor = std::io::Cursor::new(&mut buf);
-
-            // Convert to DynamicImage for JPEG encoding
-            let dynamic_image = image::DynamicImage::ImageRgba8(image);
-
-            if let Err(e) = dynamic_image.write_to(&mut cursor, image::ImageFormat::Jpeg) {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    [(header::CONTENT_TYPE, "text/plain")],
-                    format!("Failed to encode image: {}", e).into_bytes(),
-                );
-            }
-
-            (
-                StatusCode::OK,
-                [(header::CONTENT_TYPE, "image/jpeg")],
-                buf,
+    let rendered = match service.render_preview(base64_urls, format, cell_size).await {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            // Every remaining `GridError` stems from a malformed request (bad base64, no
+            // URLs, undecodable bytes); per-source size/content-type problems never reach
+            // here — they're placeholdered into the grid instead of failing the request.
+            return (
+                StatusCode::BAD_REQUEST,
+                [(header::CONTENT_TYPE, "text/plain")],
+                format!("Error: {}", e).into_bytes(),
             )
+                .into_response()
         }
-        Err(e) => (
-            StatusCode::BAD_REQUEST,
-            [(header::CONTENT_TYPE, "text/plain")],
-            format!("Error: {}", e).into_bytes(),
-        ),
+    };
+
+    let mut cache_headers = HeaderMap::new();
+    cache_headers.insert(header::ETAG, rendered.etag.parse().unwrap());
+    cache_headers.insert(
+        header::LAST_MODIFIED,
+        httpdate::fmt_http_date(rendered.rendered_at).parse().unwrap(),
+    );
+    cache_headers.insert(
+        header::CACHE_CONTROL,
+        format!("max-age={}", CACHE_MAX_AGE.as_secs()).parse().unwrap(),
+    );
+
+    if not_modified(&headers, &rendered.etag, rendered.rendered_at) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
     }
+
+    cache_headers.insert(header::CONTENT_TYPE, rendered.content_type.parse().unwrap());
+
+    (StatusCode::OK, cache_headers, rendered.bytes).into_response()
 }
 
 #[tokio::main]
@@ -61,7 +126,7 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let image_service = Arc::new(ImageService::new());
+    let image_service = Arc::new(ImageService::with_watermark(WatermarkConfig::from_env()));
 
     let app = Router::new()
         .route("/", get(hello_world))
@@ -80,7 +145,14 @@ async fn main() {
     println!("Server running on http://localhost:3000");
     println!();
     println!("Usage:");
-    println!("  GET /preview/{{base64-encoded-urls}}");
+    println!("  GET /preview/{{base64-encoded-urls}}[?format=webp|png|avif|jpeg][&size=80|160|320|640|1080]");
+    println!();
+    println!("The response format honors ?format=, then the Accept header, then falls back to JPEG.");
+    println!();
+    println!(
+        "Set IMAGEPREVIEW_WATERMARK_PATH to composite a watermark onto every grid \
+         (IMAGEPREVIEW_WATERMARK_PLACEMENT, IMAGEPREVIEW_WATERMARK_OPACITY optional)."
+    );
     println!();
     println!("Example:");
     println!("  URLS='https://example.com/1.jpg,https://example.com/2.png'");
@@ -91,3 +163,41 @@ async fn main() {
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_modified_ignores_if_modified_since_when_if_none_match_present() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, "\"other\"".parse().unwrap());
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            "Sun, 01 Jan 2096 00:00:00 GMT".parse().unwrap(),
+        );
+
+        // If-None-Match is present but doesn't match; the far-future If-Modified-Since
+        // must not be consulted as a fallback.
+        assert!(!not_modified(&headers, "\"current\"", SystemTime::now()));
+    }
+
+    #[test]
+    fn test_not_modified_matches_if_none_match() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, "\"current\"".parse().unwrap());
+
+        assert!(not_modified(&headers, "\"current\"", SystemTime::now()));
+    }
+
+    #[test]
+    fn test_not_modified_falls_back_to_if_modified_since() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            "Sun, 01 Jan 2096 00:00:00 GMT".parse().unwrap(),
+        );
+
+        assert!(not_modified(&headers, "\"current\"", SystemTime::now()));
+    }
+}